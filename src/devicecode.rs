@@ -0,0 +1,135 @@
+//! Headless OAuth 2.0 device authorization grant (RFC 8628).
+//!
+//! For servers, SSH sessions, and CI where no webview can be shown: the user is
+//! pointed at a verification URL on another device while LCAP polls the token
+//! endpoint until the grant is approved. The resulting access/refresh pair is
+//! fed into the same token-exchange pipeline as the interactive path.
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+type BoxError = Box<dyn std::error::Error>;
+
+const DEVICE_CODE_URL: &str = "https://login.microsoftonline.com/consumers/oauth2/v2.0/devicecode";
+const TOKEN_URL: &str = "https://login.microsoftonline.com/consumers/oauth2/v2.0/token";
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Number of extra seconds to back off by on a `slow_down` response.
+const SLOW_DOWN_STEP: u64 = 5;
+
+#[derive(Deserialize)]
+struct DeviceAuth {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    interval: u64
+}
+
+#[derive(Deserialize)]
+struct PollResponse {
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+    error: Option<String>
+}
+
+/// Runs the device-code grant to completion, returning the access/refresh pair.
+///
+/// Prints the user code and verification URL to stdout, then polls at the
+/// server-specified interval, backing off on `authorization_pending`/`slow_down`
+/// and surfacing any other error as terminal.
+pub fn run(
+    client: &reqwest::blocking::Client,
+    client_id: &str,
+    scope: &str
+) -> Result<(String, String), BoxError> {
+    let auth: DeviceAuth = client
+        .post(DEVICE_CODE_URL)
+        .form(&[("client_id", client_id), ("scope", scope)])
+        .send()?
+        .error_for_status()?
+        .json()?;
+
+    println!("\nTo sign in, open {} and enter code {}\n", auth.verification_uri, auth.user_code);
+
+    let mut interval = auth.interval;
+    loop {
+        sleep(Duration::from_secs(interval));
+
+        let resp: PollResponse = client
+            .post(TOKEN_URL)
+            .form(&[
+                ("grant_type", DEVICE_CODE_GRANT),
+                ("client_id", client_id),
+                ("device_code", &auth.device_code)
+            ])
+            .send()?
+            .json()?;
+
+        if let Some(access_token) = resp.access_token {
+            return finish_tokens(access_token, resp.refresh_token);
+        }
+
+        interval = poll_backoff(resp.error.as_deref(), interval)?;
+    }
+}
+
+/// Decides how long to wait before the next poll, or fails the grant.
+///
+/// `authorization_pending` keeps the current interval, `slow_down` grows it
+/// by [`SLOW_DOWN_STEP`], any other error code is terminal, and a response
+/// with neither a token nor an error is treated as a protocol violation.
+fn poll_backoff(error: Option<&str>, interval: u64) -> Result<u64, BoxError> {
+    match error {
+        Some("authorization_pending") => Ok(interval),
+        Some("slow_down") => Ok(interval + SLOW_DOWN_STEP),
+        Some(err) => Err(err.to_owned().into()),
+        None => Err("Device-code token endpoint returned neither token nor error".into())
+    }
+}
+
+/// Builds the final access/refresh pair, rejecting a missing refresh token
+/// instead of silently substituting an empty string — which would otherwise
+/// fail opaquely against `oauth20_token.srf` on the next silent refresh.
+fn finish_tokens(access_token: String, refresh_token: Option<String>) -> Result<(String, String), BoxError> {
+    let refresh_token = refresh_token.ok_or("Device-code grant completed without a refresh token")?;
+    Ok((access_token, refresh_token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn authorization_pending_keeps_the_same_interval() {
+        assert_eq!(poll_backoff(Some("authorization_pending"), 5).unwrap(), 5);
+    }
+
+    #[test]
+    fn slow_down_grows_the_interval() {
+        assert_eq!(poll_backoff(Some("slow_down"), 5).unwrap(), 5 + SLOW_DOWN_STEP);
+    }
+
+    #[test]
+    fn other_error_codes_are_terminal() {
+        assert!(poll_backoff(Some("expired_token"), 5).is_err());
+    }
+
+    #[test]
+    fn missing_error_and_missing_token_is_terminal() {
+        assert!(poll_backoff(None, 5).is_err());
+    }
+
+    #[test]
+    fn finish_tokens_succeeds_with_a_refresh_token() {
+        let (access, refresh) = finish_tokens("access-1".to_owned(), Some("refresh-1".to_owned())).unwrap();
+        assert_eq!(access, "access-1");
+        assert_eq!(refresh, "refresh-1");
+    }
+
+    #[test]
+    fn finish_tokens_rejects_a_missing_refresh_token() {
+        assert!(finish_tokens("access-1".to_owned(), None).is_err());
+    }
+}
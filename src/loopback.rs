@@ -0,0 +1,120 @@
+//! Loopback-redirect capture via a local one-shot HTTP listener.
+//!
+//! This is the standard OAuth 2.0 native-app flow: the authorize request names
+//! `http://127.0.0.1:<port>/` as its redirect, and the provider steers the
+//! browser there with the `code`/`error` in the query string. Capturing from
+//! the socket rather than the redirect page's HTML keeps us decoupled from
+//! upstream changes to `oauth20_desktop.srf`.
+
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::str::FromStr;
+
+const RESPONSE_BODY: &str =
+    "<!doctype html><html><body><p>Login complete \u{2014} you may close this window.</p></body></html>";
+
+/// Accepts connections until one carries a parseable `code_tag`/`error_tag`
+/// query param, replies to each with a minimal close-me page, and returns the
+/// captured `(code, error)` values.
+///
+/// Some browser/webview engines issue a stray pre-connect or a favicon
+/// request to the redirect origin around the same time as the real
+/// navigation; taking only the first connection risks capturing nothing and
+/// aborting the login before the real redirect arrives, so every connection
+/// without a `code`/`error` is answered and discarded rather than returned.
+pub fn capture(
+    listener: &TcpListener,
+    code_tag: &str,
+    error_tag: &str
+) -> std::io::Result<(Option<String>, Option<String>)> {
+    loop {
+        let (mut stream, _) = listener.accept()?;
+
+        let mut buf = [0u8; 2048];
+        let n = stream.read(&mut buf)?;
+        let request = String::from_utf8_lossy(&buf[..n]);
+
+        // Request line is "METHOD <origin-form target> HTTP/1.1"; resolve the
+        // origin-form target against a dummy base so we can reuse url's parser.
+        let target = request.split_whitespace().nth(1).unwrap_or("/");
+
+        let (mut code, mut error) = (None, None);
+        if let Ok(u) = url::Url::from_str(&format!("http://127.0.0.1{target}")) {
+            for (k, v) in u.query_pairs() {
+                if k == code_tag {
+                    code = Some(v.into_owned());
+                } else if k == error_tag {
+                    error = Some(v.into_owned());
+                }
+            }
+        }
+
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            RESPONSE_BODY.len(),
+            RESPONSE_BODY
+        );
+        stream.write_all(response.as_bytes())?;
+        stream.flush()?;
+
+        if code.is_some() || error.is_some() {
+            return Ok((code, error));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::net::TcpStream;
+    use std::thread;
+
+    /// Connects, sends a bare `GET <target>` request line, and drains the
+    /// response so the server's write doesn't block on a full socket buffer.
+    fn send_request(addr: SocketAddr, target: &str) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(format!("GET {target} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n").as_bytes()).unwrap();
+        let mut buf = [0u8; 1024];
+        let _ = stream.read(&mut buf);
+    }
+
+    #[test]
+    fn captures_code_from_query_string() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || send_request(addr, "/?code=abc123"));
+
+        let (code, error) = capture(&listener, "code", "error").unwrap();
+        assert_eq!(code.as_deref(), Some("abc123"));
+        assert_eq!(error, None);
+    }
+
+    #[test]
+    fn captures_error_from_query_string() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || send_request(addr, "/?error=access_denied"));
+
+        let (code, error) = capture(&listener, "code", "error").unwrap();
+        assert_eq!(code, None);
+        assert_eq!(error.as_deref(), Some("access_denied"));
+    }
+
+    #[test]
+    fn keeps_accepting_until_the_real_redirect_arrives() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            // A stray favicon probe carries neither tag...
+            send_request(addr, "/favicon.ico");
+            // ...so capture() must keep accepting rather than returning (None, None).
+            send_request(addr, "/?code=real-code");
+        });
+
+        let (code, error) = capture(&listener, "code", "error").unwrap();
+        assert_eq!(code.as_deref(), Some("real-code"));
+        assert_eq!(error, None);
+    }
+}
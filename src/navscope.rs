@@ -0,0 +1,101 @@
+//! Allow/deny scope for webview navigation, modeled on Tauri's `FsScope`.
+//!
+//! The embedded webview follows any navigation by default, which lets a
+//! malicious page drag the in-flight auth session to an arbitrary origin. A
+//! `NavScope` restricts that: a URL is permitted only when its host matches an
+//! allow rule and no deny rule, with deny always winning.
+
+use glob::Pattern;
+
+/// A single host rule, either a glob (contains `*`/`?`) or a plain host suffix.
+enum Rule {
+    Glob(Pattern),
+    Suffix(String)
+}
+
+impl Rule {
+    fn parse(raw: &str) -> Self {
+        if raw.contains('*') || raw.contains('?') {
+            match Pattern::new(raw) {
+                Ok(p) => Rule::Glob(p),
+                // A malformed glob can never match; fall back to an exact suffix.
+                Err(_) => Rule::Suffix(raw.to_owned())
+            }
+        } else {
+            Rule::Suffix(raw.to_owned())
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            Rule::Glob(p) => p.matches(host),
+            Rule::Suffix(s) => host == s || host.ends_with(&format!(".{s}"))
+        }
+    }
+}
+
+/// A compiled set of allow/deny host rules.
+pub struct NavScope {
+    allow: Vec<Rule>,
+    deny: Vec<Rule>
+}
+
+impl NavScope {
+    /// Compiles an allow/deny rule set from host-suffix or glob patterns.
+    pub fn new(allow: &[String], deny: &[String]) -> Self {
+        Self {
+            allow: allow.iter().map(|r| Rule::parse(r)).collect(),
+            deny: deny.iter().map(|r| Rule::parse(r)).collect()
+        }
+    }
+
+    /// Returns whether navigation to `url` is permitted. Deny takes precedence,
+    /// and a URL without a host is never permitted.
+    pub fn is_allowed(&self, url: &str) -> bool {
+        let Some(host) = url::Url::parse(url).ok().and_then(|u| u.host_str().map(str::to_owned)) else {
+            return false;
+        };
+
+        if self.deny.iter().any(|r| r.matches(&host)) {
+            return false;
+        }
+
+        self.allow.iter().any(|r| r.matches(&host))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suffix_matches_host_and_subdomains() {
+        let scope = NavScope::new(&["login.live.com".to_owned()], &[]);
+        assert!(scope.is_allowed("https://login.live.com/oauth20_authorize.srf"));
+        assert!(scope.is_allowed("https://foo.login.live.com/"));
+        assert!(!scope.is_allowed("https://evil.com/"));
+        // A bare suffix must not match an unrelated host that merely contains the text.
+        assert!(!scope.is_allowed("https://notlogin.live.com.evil.com/"));
+    }
+
+    #[test]
+    fn deny_takes_precedence_over_allow() {
+        let scope = NavScope::new(&["live.com".to_owned()], &["evil.live.com".to_owned()]);
+        assert!(scope.is_allowed("https://login.live.com/"));
+        assert!(!scope.is_allowed("https://evil.live.com/"));
+    }
+
+    #[test]
+    fn glob_rule_matches() {
+        let scope = NavScope::new(&["*.microsoftonline.com".to_owned()], &[]);
+        assert!(scope.is_allowed("https://login.microsoftonline.com/"));
+        assert!(!scope.is_allowed("https://microsoftonline.com/"));
+    }
+
+    #[test]
+    fn hostless_url_rejected() {
+        let scope = NavScope::new(&["login.live.com".to_owned()], &[]);
+        assert!(!scope.is_allowed("about:blank"));
+        assert!(!scope.is_allowed("not a url"));
+    }
+}
@@ -0,0 +1,60 @@
+//! PKCE (RFC 7636) helpers for secret-less authorization-code exchange.
+//!
+//! Mirrors the browser flow in msal_browser: a random `code_verifier` is held
+//! in memory while its S256 `code_challenge` rides along with the authorize
+//! request, proving possession when the code is later redeemed.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::Rng;
+use sha2::Digest;
+use sha2::Sha256;
+
+/// Unreserved characters permitted in a `code_verifier` (RFC 7636 §4.1).
+const VERIFIER_CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-._~";
+
+/// Length of the generated verifier; within the 43..=128 range the RFC allows.
+const VERIFIER_LEN: usize = 96;
+
+/// A freshly generated PKCE pair tying one authorize request to its exchange.
+pub struct Pkce {
+    /// The secret held until the code is redeemed.
+    pub verifier: String,
+    /// The `base64url(SHA256(verifier))` sent on the authorize URL.
+    pub challenge: String
+}
+
+impl Pkce {
+    /// Generates a cryptographically random verifier and its S256 challenge.
+    pub fn generate() -> Self {
+        let mut rng = rand::thread_rng();
+        let verifier: String = (0..VERIFIER_LEN)
+            .map(|_| VERIFIER_CHARSET[rng.gen_range(0..VERIFIER_CHARSET.len())] as char)
+            .collect();
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+
+        Self { verifier, challenge }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifier_length_and_charset() {
+        let pkce = Pkce::generate();
+        assert_eq!(pkce.verifier.len(), VERIFIER_LEN);
+        assert!((43..=128).contains(&pkce.verifier.len()));
+        assert!(pkce.verifier.bytes().all(|b| VERIFIER_CHARSET.contains(&b)));
+    }
+
+    #[test]
+    fn challenge_is_s256_of_verifier() {
+        let pkce = Pkce::generate();
+        let expected = URL_SAFE_NO_PAD.encode(Sha256::digest(pkce.verifier.as_bytes()));
+        assert_eq!(pkce.challenge, expected);
+        // base64url without padding never contains '=', '+', or '/'.
+        assert!(!pkce.challenge.contains(['=', '+', '/']));
+    }
+}
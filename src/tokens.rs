@@ -0,0 +1,228 @@
+//! Post-capture token exchange pipeline.
+//!
+//! Turns the raw OAuth `code` captured from the login flow into a
+//! Minecraft-ready credential by walking the Xbox Live / XSTS ladder, the
+//! same sequence implemented by xal-rs.
+
+use serde::Deserialize;
+use serde::Serialize;
+
+type BoxError = Box<dyn std::error::Error>;
+
+const LIVE_TOKEN_URL: &str = "https://login.live.com/oauth20_token.srf";
+const XBL_AUTH_URL: &str = "https://user.auth.xboxlive.com/user/authenticate";
+const XSTS_AUTH_URL: &str = "https://xsts.auth.xboxlive.com/xsts/authorize";
+
+/// The fully exchanged credential emitted when `--exchange` is set.
+#[derive(Serialize)]
+pub struct TokenResult {
+    /// The `XBL3.0 x=<uhs>;<token>` header value accepted by Minecraft services.
+    pub authorization: String,
+    /// The Live refresh token, usable to silently mint new credentials later.
+    pub refresh_token: String
+}
+
+#[derive(Deserialize)]
+struct OAuthToken {
+    access_token: String,
+    refresh_token: String
+}
+
+#[derive(Deserialize)]
+struct XboxToken {
+    #[serde(rename = "Token")]
+    token: String,
+    #[serde(rename = "DisplayClaims")]
+    display_claims: DisplayClaims
+}
+
+#[derive(Deserialize)]
+struct DisplayClaims {
+    xui: Vec<Xui>
+}
+
+#[derive(Deserialize)]
+struct Xui {
+    uhs: String
+}
+
+/// Runs the whole `code` -> Live -> Xbox Live -> XSTS chain and returns the
+/// combined credential.
+pub fn exchange(
+    client: &reqwest::blocking::Client,
+    code: &str,
+    client_id: &str,
+    redirect_uri: &str,
+    code_verifier: Option<&str>
+) -> Result<TokenResult, BoxError> {
+    let oauth = oauth_code(client, code, client_id, redirect_uri, code_verifier)?;
+    // The legacy MBI_SSL access token is passed to Xbox Live verbatim.
+    xbox_ladder(client, oauth, false)
+}
+
+/// Mints a fresh credential from a previously stored refresh token without any
+/// user interaction.
+pub fn refresh(
+    client: &reqwest::blocking::Client,
+    refresh_token: &str,
+    client_id: &str,
+    redirect_uri: &str
+) -> Result<TokenResult, BoxError> {
+    let oauth = oauth_refresh(client, refresh_token, client_id, redirect_uri)?;
+    // A refresh token minted by the legacy flow yields another raw MBI_SSL ticket.
+    xbox_ladder(client, oauth, false)
+}
+
+/// Builds a credential directly from an already-obtained access/refresh pair,
+/// as produced by the device-code flow.
+pub fn from_access_token(
+    client: &reqwest::blocking::Client,
+    access_token: String,
+    refresh_token: String
+) -> Result<TokenResult, BoxError> {
+    // The device-code flow returns a v2.0 MSA token, which Xbox Live expects
+    // prefixed with `d=`.
+    xbox_ladder(client, OAuthToken { access_token, refresh_token }, true)
+}
+
+/// Walks the Live access token up through Xbox Live and XSTS into a credential.
+///
+/// `v2_token` selects the `RpsTicket` encoding: raw for legacy MBI_SSL tickets,
+/// `d=<token>` for v2.0 MSA tokens.
+fn xbox_ladder(client: &reqwest::blocking::Client, oauth: OAuthToken, v2_token: bool) -> Result<TokenResult, BoxError> {
+    let rps_ticket = rps_ticket(&oauth.access_token, v2_token);
+
+    let xbl = xbl_authenticate(client, &rps_ticket)?;
+    let uhs = first_uhs(&xbl.display_claims.xui)?;
+    let xsts = xsts_authorize(client, &xbl.token)?;
+
+    Ok(TokenResult {
+        authorization: format!("XBL3.0 x={uhs};{}", xsts.token),
+        refresh_token: oauth.refresh_token
+    })
+}
+
+/// Encodes the Live access token as the `RpsTicket` Xbox Live expects: raw for
+/// legacy MBI_SSL tickets, `d=<token>` for v2.0 MSA tokens.
+fn rps_ticket(access_token: &str, v2_token: bool) -> String {
+    if v2_token {
+        format!("d={access_token}")
+    } else {
+        access_token.to_owned()
+    }
+}
+
+/// Pulls the user hash (`uhs`) out of the first `xui` entry, or an error when
+/// Xbox Live's response carries none.
+fn first_uhs(xui: &[Xui]) -> Result<String, BoxError> {
+    xui.first().map(|it| it.uhs.clone()).ok_or_else(|| "Xbox Live response carried no user hash".into())
+}
+
+fn oauth_code(
+    client: &reqwest::blocking::Client,
+    code: &str,
+    client_id: &str,
+    redirect_uri: &str,
+    code_verifier: Option<&str>
+) -> Result<OAuthToken, BoxError> {
+    let mut params = vec![
+        ("grant_type", "authorization_code"),
+        ("client_id", client_id),
+        ("code", code),
+        ("redirect_uri", redirect_uri),
+    ];
+
+    if let Some(verifier) = code_verifier {
+        params.push(("code_verifier", verifier));
+    }
+
+    post_token(client, &params)
+}
+
+fn oauth_refresh(
+    client: &reqwest::blocking::Client,
+    refresh_token: &str,
+    client_id: &str,
+    redirect_uri: &str
+) -> Result<OAuthToken, BoxError> {
+    let params = [
+        ("grant_type", "refresh_token"),
+        ("client_id", client_id),
+        ("refresh_token", refresh_token),
+        ("redirect_uri", redirect_uri)
+    ];
+
+    post_token(client, &params)
+}
+
+fn post_token(client: &reqwest::blocking::Client, params: &[(&str, &str)]) -> Result<OAuthToken, BoxError> {
+    Ok(client
+        .post(LIVE_TOKEN_URL)
+        .form(params)
+        .send()?
+        .error_for_status()?
+        .json()?)
+}
+
+fn xbl_authenticate(client: &reqwest::blocking::Client, rps_ticket: &str) -> Result<XboxToken, BoxError> {
+    let body = serde_json::json!({
+        "Properties": {
+            "AuthMethod": "RPS",
+            "SiteName": "user.auth.xboxlive.com",
+            "RpsTicket": rps_ticket
+        },
+        "RelyingParty": "http://auth.xboxlive.com",
+        "TokenType": "JWT"
+    });
+
+    Ok(client
+        .post(XBL_AUTH_URL)
+        .json(&body)
+        .send()?
+        .error_for_status()?
+        .json()?)
+}
+
+fn xsts_authorize(client: &reqwest::blocking::Client, user_token: &str) -> Result<XboxToken, BoxError> {
+    let body = serde_json::json!({
+        "Properties": {
+            "SandboxId": "RETAIL",
+            "UserTokens": [user_token]
+        },
+        "RelyingParty": "rp://api.minecraftservices.com/",
+        "TokenType": "JWT"
+    });
+
+    Ok(client
+        .post(XSTS_AUTH_URL)
+        .json(&body)
+        .send()?
+        .error_for_status()?
+        .json()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rps_ticket_passes_legacy_token_through_unchanged() {
+        assert_eq!(rps_ticket("legacy-access-token", false), "legacy-access-token");
+    }
+
+    #[test]
+    fn rps_ticket_prefixes_v2_token_with_d_equals() {
+        assert_eq!(rps_ticket("v2-access-token", true), "d=v2-access-token");
+    }
+
+    #[test]
+    fn first_uhs_returns_the_first_entry() {
+        let xui = vec![Xui { uhs: "hash-1".to_owned() }, Xui { uhs: "hash-2".to_owned() }];
+        assert_eq!(first_uhs(&xui).unwrap(), "hash-1");
+    }
+
+    #[test]
+    fn first_uhs_errors_when_xui_is_empty() {
+        assert!(first_uhs(&[]).is_err());
+    }
+}
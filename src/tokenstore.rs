@@ -0,0 +1,221 @@
+//! Encrypted at-rest store for refresh/XSTS material, keyed to the partition.
+//!
+//! The webview's cookies already live under the per-UUID partition directory in
+//! cleartext; a refresh token dropped beside them deserves better. Each write is
+//! an AES-256-GCM blob (`nonce || ciphertext`) with a fresh 96-bit nonce. The
+//! key comes from OS secret storage (keychain / secret-service / DPAPI) and
+//! falls back to a random key file in the partition directory when no OS store
+//! is reachable, as paket does.
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use aes_gcm::aead::Aead;
+use aes_gcm::aead::OsRng;
+use aes_gcm::AeadCore;
+use aes_gcm::Aes256Gcm;
+use aes_gcm::Key;
+use aes_gcm::KeyInit;
+use aes_gcm::Nonce;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::RngCore;
+use serde::Deserialize;
+use serde::Serialize;
+
+type BoxError = Box<dyn std::error::Error>;
+
+const STORE_FILE: &str = "tokenstore.bin";
+const KEY_FILE: &str = "tokenstore.key";
+const KEYRING_SERVICE: &str = "moe.skjsjhb.LCAP";
+const NONCE_LEN: usize = 12;
+
+/// Persisted credential material for one storage partition.
+#[derive(Serialize, Deserialize)]
+pub struct StoredTokens {
+    /// The Live refresh token used to silently re-mint credentials.
+    pub refresh_token: String,
+    /// The last `XBL3.0` authorization header, cached for reference.
+    pub authorization: Option<String>
+}
+
+/// Handle to the encrypted store for a single partition.
+pub struct Store {
+    dir: PathBuf,
+    keyring_user: String
+}
+
+impl Store {
+    /// Binds a store to `partition_dir`, keyed in OS secret storage by `partition_id`.
+    pub fn new(partition_dir: &Path, partition_id: &str) -> Self {
+        Self {
+            dir: partition_dir.to_path_buf(),
+            keyring_user: partition_id.to_owned()
+        }
+    }
+
+    /// Decrypts and returns the stored tokens, or `None` when absent or unreadable.
+    ///
+    /// Uses a read-only key lookup: a transient keyring failure (locked
+    /// secret-service, D-Bus unreachable, ...) must never be mistaken for
+    /// "no key was ever saved" and fabricate a fresh one, or the existing
+    /// blob becomes permanently undecryptable.
+    pub fn load(&self) -> Option<StoredTokens> {
+        let blob = fs::read(self.dir.join(STORE_FILE)).ok()?;
+        if blob.len() <= NONCE_LEN {
+            return None;
+        }
+
+        let (nonce, ciphertext) = blob.split_at(NONCE_LEN);
+        let key = self.read_key()?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let plain = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()?;
+        serde_json::from_slice(&plain).ok()
+    }
+
+    /// Encrypts and writes the tokens, overwriting any previous blob.
+    pub fn save(&self, tokens: &StoredTokens) -> Result<(), BoxError> {
+        let key = self.load_or_create_key()?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let plain = serde_json::to_vec(tokens)?;
+        let ciphertext = cipher
+            .encrypt(&nonce, plain.as_ref())
+            .map_err(|e| format!("token encryption failed: {e}"))?;
+
+        fs::create_dir_all(&self.dir)?;
+        let mut blob = nonce.to_vec();
+        blob.extend_from_slice(&ciphertext);
+        let store_path = self.dir.join(STORE_FILE);
+        fs::write(&store_path, blob)?;
+        restrict_permissions(&store_path)?;
+
+        Ok(())
+    }
+
+    /// Looks up the key without ever creating one, so a transient read
+    /// failure surfaces as "no key" rather than overwriting the real key.
+    /// Only [`Store::load_or_create_key`] (used by [`Store::save`]) may
+    /// persist a newly generated key.
+    fn read_key(&self) -> Option<[u8; 32]> {
+        match keyring::Entry::new(KEYRING_SERVICE, &self.keyring_user) {
+            Ok(entry) => match entry.get_password() {
+                Ok(s) => decode_key(&s),
+                Err(_) => self.read_file_key()
+            },
+            Err(_) => self.read_file_key()
+        }
+    }
+
+    fn read_file_key(&self) -> Option<[u8; 32]> {
+        let bytes = fs::read(self.dir.join(KEY_FILE)).ok()?;
+        <[u8; 32]>::try_from(bytes.as_slice()).ok()
+    }
+
+    /// Resolves the 256-bit key, preferring OS secret storage and falling back
+    /// to a random key file in the partition directory. Only called from the
+    /// write path: creating a key here is safe because there is fresh
+    /// ciphertext about to be written under it.
+    fn load_or_create_key(&self) -> Result<[u8; 32], BoxError> {
+        if let Ok(entry) = keyring::Entry::new(KEYRING_SERVICE, &self.keyring_user) {
+            match entry.get_password() {
+                Ok(s) => {
+                    if let Some(key) = decode_key(&s) {
+                        return Ok(key);
+                    }
+                }
+                Err(keyring::Error::NoEntry) => {
+                    let key = random_key();
+                    if entry.set_password(&URL_SAFE_NO_PAD.encode(key)).is_ok() {
+                        return Ok(key);
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+
+        self.file_key()
+    }
+
+    fn file_key(&self) -> Result<[u8; 32], BoxError> {
+        let path = self.dir.join(KEY_FILE);
+        if let Some(key) = self.read_file_key() {
+            return Ok(key);
+        }
+
+        let key = random_key();
+        fs::create_dir_all(&self.dir)?;
+        fs::write(&path, key)?;
+        restrict_permissions(&path)?;
+        Ok(key)
+    }
+}
+
+fn random_key() -> [u8; 32] {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+/// Restricts a file to owner-only access on Unix; a no-op elsewhere.
+///
+/// The key file and the encrypted blob both defeat the point of chunk0-4 if
+/// another local account can read them, so neither should inherit the
+/// umask-derived default mode.
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> std::io::Result<()> {
+    use std::fs::Permissions;
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+fn decode_key(encoded: &str) -> Option<[u8; 32]> {
+    URL_SAFE_NO_PAD.decode(encoded).ok().and_then(|b| <[u8; 32]>::try_from(b.as_slice()).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encrypts and decrypts with a fixed key so the round trip is hermetic and
+    /// never touches OS secret storage.
+    fn cipher(key: &[u8; 32]) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+    }
+
+    #[test]
+    fn encrypt_decrypt_round_trip() {
+        let key = [7u8; 32];
+        let cipher = cipher(&key);
+        let tokens = StoredTokens {
+            refresh_token: "refresh-123".to_owned(),
+            authorization: Some("XBL3.0 x=uhs;token".to_owned())
+        };
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let plain = serde_json::to_vec(&tokens).unwrap();
+        let ciphertext = cipher.encrypt(&nonce, plain.as_ref()).unwrap();
+
+        // The ciphertext must not leak the plaintext refresh token.
+        assert!(!ciphertext.windows(11).any(|w| w == b"refresh-123"));
+
+        let decrypted = cipher.decrypt(&nonce, ciphertext.as_ref()).unwrap();
+        let restored: StoredTokens = serde_json::from_slice(&decrypted).unwrap();
+        assert_eq!(restored.refresh_token, "refresh-123");
+        assert_eq!(restored.authorization.as_deref(), Some("XBL3.0 x=uhs;token"));
+    }
+
+    #[test]
+    fn wrong_key_fails_to_decrypt() {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher(&[1u8; 32]).encrypt(&nonce, b"secret".as_ref()).unwrap();
+        assert!(cipher(&[2u8; 32]).decrypt(&nonce, ciphertext.as_ref()).is_err());
+    }
+}